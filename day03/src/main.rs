@@ -1,9 +1,6 @@
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 
-extern crate geo;
-extern crate line_intersection;
-
 fn read_lines(filename: &str) -> impl Iterator<Item=String> {
     let file = File::open(filename).unwrap();
     let reader = BufReader::new(file);
@@ -19,6 +16,7 @@ struct Point {
 struct Wire {
     p0: Point,
     p1: Point,
+    steps_at_start: i64,
 }
 
 fn parse_segment(s: &str) -> Point {
@@ -38,58 +36,86 @@ fn parse_wire(str: &str) -> Vec<Wire> {
     let segments = str.split(",").map(|x| parse_segment(x));
     let mut x = 0;
     let mut y = 0;
+    let mut steps = 0;
     let mut wire = Vec::new();
     for s in segments {
         wire.push(Wire {
             p0: Point {x: x, y: y},
-            p1: Point {x: x + s.x, y: y + s.y}
+            p1: Point {x: x + s.x, y: y + s.y},
+            steps_at_start: steps,
         });
         x += s.x;
         y += s.y;
+        steps += i64::abs(s.x) + i64::abs(s.y);
     }
     wire
 }
 
+fn is_horizontal(wire: &Wire) -> bool {
+    wire.p0.y == wire.p1.y
+}
+
+fn is_vertical(wire: &Wire) -> bool {
+    wire.p0.x == wire.p1.x
+}
+
+/// Finds where two axis-aligned segments cross, using exact integer math:
+/// the puzzle's wires are always either horizontal or vertical, so a crossing
+/// only happens between one of each, and the intersection point is wherever
+/// the vertical segment's fixed `x` falls inside the horizontal segment's
+/// span (and vice versa for `y`).
 fn get_intersection(wire: &Wire, other: &Wire) -> Option<Point> {
-    use line_intersection::{LineInterval};
-
-    let seg1 = LineInterval::line_segment(geo::Line {
-        start: (wire.p0.x as f64, wire.p0.y as f64).into(),
-        end: (wire.p1.x as f64, wire.p1.y as f64).into(),
-    });
-
-    let seg2 = LineInterval::line_segment(geo::Line {
-        start: (other.p0.x as f64, other.p0.y as f64).into(),
-        end: (other.p1.x as f64, other.p1.y as f64).into(),
-    });
-
-    let intersection = seg1.relate(&seg2).unique_intersection();
-    match intersection {
-        None => None,
-        Some(geo::Point(geo::Coordinate {x, y})) => Some(Point {x: x.round() as i64, y: y.round() as i64}),
+    let (h, v) = if is_horizontal(wire) && is_vertical(other) {
+        (wire, other)
+    } else if is_vertical(wire) && is_horizontal(other) {
+        (other, wire)
+    } else {
+        return None;
+    };
+
+    let (hx_min, hx_max) = (h.p0.x.min(h.p1.x), h.p0.x.max(h.p1.x));
+    let (vy_min, vy_max) = (v.p0.y.min(v.p1.y), v.p0.y.max(v.p1.y));
+    let (hy, vx) = (h.p0.y, v.p0.x);
+
+    if vx >= hx_min && vx <= hx_max && hy >= vy_min && hy <= vy_max {
+        Some(Point { x: vx, y: hy })
+    } else {
+        None
     }
 }
 
+/// Steps taken along `wire` to reach `p`, which must lie on the segment.
+fn steps_to_point(wire: &Wire, p: &Point) -> i64 {
+    wire.steps_at_start + i64::abs(p.x - wire.p0.x) + i64::abs(p.y - wire.p0.y)
+}
+
 fn main() {
     let lines: Vec<String> = read_lines("input.in").collect();
 
     let wire1 = parse_wire(&lines[0]);
     let wire2 = parse_wire(&lines[1]);
 
-    let mut dist = 999999999;
+    let mut best_dist = i64::MAX;
+    let mut best_steps = i64::MAX;
     for a in &wire1 {
         for b in &wire2 {
-            let isect = get_intersection(&a, &b);
-            match isect {
-                Some(p) => {
-                    let new_dist = i64::abs(p.x) + i64::abs(p.y);
-                    if new_dist < dist && new_dist > 0 {
-                        dist = new_dist;
-                    }
-                },
-                None => {},
+            if let Some(p) = get_intersection(a, b) {
+                if p.x == 0 && p.y == 0 {
+                    continue;
+                }
+
+                let dist = i64::abs(p.x) + i64::abs(p.y);
+                if dist < best_dist {
+                    best_dist = dist;
+                }
+
+                let steps = steps_to_point(a, &p) + steps_to_point(b, &p);
+                if steps < best_steps {
+                    best_steps = steps;
+                }
             }
         }
     }
-    println!("Best dist: {}", dist);
+    println!("Best dist: {}", best_dist);
+    println!("Best steps: {}", best_steps);
 }
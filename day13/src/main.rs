@@ -1,8 +1,11 @@
 use std::fs::File;
 use std::io::{BufRead, BufReader};
-use crate::MachineStatus::{BadOpcode, Finished, Blocked};
-use std::collections::HashMap;
+use crate::MachineStatus::{Finished, Blocked};
+use std::collections::{HashMap, VecDeque};
 use std::cmp::Ordering;
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::sync::mpsc::{Receiver, Sender};
 
 
 
@@ -18,100 +21,216 @@ enum MachineStatus {
     Runnable,
     Blocked,
     Finished,
-    BadOpcode(i64),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum MachineError {
+    InvalidMode(usize),
+    NegativeAddress(i64, usize),
+    AddressOutOfBounds(usize),
+    BadOpcode(i64, usize),
+}
+
+/// The I/O transport a `Machine` reads input from and writes output to.
+/// Decouples the interpreter from any particular way of wiring machines
+/// together, the way a client layer can offer both synchronous and
+/// asynchronous transports behind one interface.
+trait IntcodeIo {
+    fn read(&mut self) -> Option<i64>;
+    fn write(&mut self, v: i64);
+}
+
+/// A FIFO-queue `IntcodeIo`, for wiring machines together in-process like a
+/// blocking, synchronous transport.
+struct QueueIo {
+    input: Rc<RefCell<VecDeque<i64>>>,
+    output: Rc<RefCell<VecDeque<i64>>>,
+}
+
+impl IntcodeIo for QueueIo {
+    fn read(&mut self) -> Option<i64> {
+        self.input.borrow_mut().pop_front()
+    }
+
+    fn write(&mut self, v: i64) {
+        self.output.borrow_mut().push_back(v);
+    }
+}
+
+/// A handle onto a `QueueIo`'s queues, for the code driving a `Machine` to
+/// push input and drain output without reaching into the machine itself.
+struct QueueHandle {
+    input: Rc<RefCell<VecDeque<i64>>>,
+    output: Rc<RefCell<VecDeque<i64>>>,
+}
+
+impl QueueHandle {
+    fn push_input(&self, v: i64) {
+        self.input.borrow_mut().push_back(v);
+    }
+
+    fn drain_outputs(&self) -> Vec<i64> {
+        self.output.borrow_mut().drain(..).collect()
+    }
+}
+
+/// Builds a connected `QueueIo`/`QueueHandle` pair sharing a fresh pair of queues.
+fn queue_io() -> (QueueIo, QueueHandle) {
+    let input = Rc::new(RefCell::new(VecDeque::new()));
+    let output = Rc::new(RefCell::new(VecDeque::new()));
+    (
+        QueueIo { input: input.clone(), output: output.clone() },
+        QueueHandle { input, output },
+    )
+}
+
+/// An `mpsc`-backed `IntcodeIo`, like an asynchronous transport: a read with
+/// nothing waiting reports `None` (and so a `Machine` goes `Blocked`) instead
+/// of parking the thread.
+struct ChannelIo {
+    input: Receiver<i64>,
+    output: Sender<i64>,
+}
+
+impl IntcodeIo for ChannelIo {
+    fn read(&mut self) -> Option<i64> {
+        self.input.try_recv().ok()
+    }
+
+    fn write(&mut self, v: i64) {
+        let _ = self.output.send(v);
+    }
+}
+
+/// Chains `programs.len()` machines output-to-input, wiring the last
+/// machine's output back into the first machine's input so feedback-loop
+/// topologies (day 7's amplifiers, day 23's network of computers) can be
+/// built without manually shuttling values between runs. Returns the
+/// machines alongside each one's input queue, so callers can seed phase
+/// settings (or other priming input) before running them.
+fn chain_machines(programs: &[Vec<i64>]) -> (Vec<Machine>, Vec<Rc<RefCell<VecDeque<i64>>>>) {
+    let n = programs.len();
+    let queues: Vec<Rc<RefCell<VecDeque<i64>>>> =
+        (0..n).map(|_| Rc::new(RefCell::new(VecDeque::new()))).collect();
+
+    let machines = (0..n).map(|i| {
+        let input = queues[i].clone();
+        let output = queues[(i + 1) % n].clone();
+        Machine::new(&programs[i], Box::new(QueueIo { input, output }))
+    }).collect();
+
+    (machines, queues)
 }
 
 struct Machine {
     mem: Vec<i64>,
     pos: usize,
-    inputs: Vec<i64>,
-    outputs: Vec<i64>,
-    input_pos: usize,
-    output_pos: usize,
+    io: Box<dyn IntcodeIo>,
     status: MachineStatus,
     relative_base: i64,
 }
 
 impl Machine {
-    fn new(mem: &Vec<i64>) -> Machine {
-        let mut new_mem = Vec::new();
-        new_mem.extend(mem);
-        for _ in 0..1000 {
-            new_mem.push(0);
-        }
-
+    fn new(mem: &Vec<i64>, io: Box<dyn IntcodeIo>) -> Machine {
         Machine {
-            mem: new_mem,
+            mem: mem.clone(),
             pos: 0,
-            inputs: Vec::new(),
-            outputs: Vec::new(),
-            input_pos: 0,
-            output_pos: 0,
+            io,
             status: MachineStatus::Runnable,
             relative_base: 0,
         }
     }
 
-    fn arg(&mut self, arg: usize) -> &mut i64 {
-        let addressing: i64 = self.mem[self.pos] / 100;
+    fn arg(&mut self, arg: usize) -> Result<&mut i64, MachineError> {
+        let word_pos = self.pos + 1 + arg;
+        let addressing = self.fetch(self.pos)? / 100;
         let mode = (addressing / 10_i64.pow(arg as u32)) % 10;
         match mode {
             0 => {
-                let addr = self.mem[self.pos + 1 + arg];
-                &mut self.mem[addr as usize]
+                let addr = self.fetch(word_pos)?;
+                self.cell_mut(addr)
             },
-            1 => &mut self.mem[self.pos + 1 + arg],
+            1 => self.word_mut(word_pos),
             2 => {
-                let val = self.mem[self.pos + 1 + arg];
-                &mut self.mem[(self.relative_base + val) as usize]
+                let val = self.fetch(word_pos)?;
+                self.cell_mut(self.relative_base + val)
             }
-            _ => { panic!(); }
+            _ => Err(MachineError::InvalidMode(self.pos)),
+        }
+    }
+
+    /// Reads the program word at `pos`, bounds-checked: unlike `cell_mut`,
+    /// this never grows `mem`, since a missing instruction/operand word
+    /// means the program is truncated, not that a data cell hasn't been
+    /// touched yet.
+    fn fetch(&self, pos: usize) -> Result<i64, MachineError> {
+        self.mem.get(pos).copied().ok_or(MachineError::AddressOutOfBounds(pos))
+    }
+
+    /// Like `fetch`, but for the immediate-mode case where the operand word
+    /// itself (not the cell it would point to) is the read/write target.
+    fn word_mut(&mut self, pos: usize) -> Result<&mut i64, MachineError> {
+        self.mem.get_mut(pos).ok_or(MachineError::AddressOutOfBounds(pos))
+    }
+
+    /// Returns a mutable reference to `mem[addr]`, growing the backing store
+    /// with zeroes if `addr` falls past its current end.
+    fn cell_mut(&mut self, addr: i64) -> Result<&mut i64, MachineError> {
+        if addr < 0 {
+            return Err(MachineError::NegativeAddress(addr, self.pos));
+        }
+        let addr = addr as usize;
+        if addr >= self.mem.len() {
+            self.mem.resize(addr + 1, 0);
         }
+        Ok(&mut self.mem[addr])
     }
 
-    fn run(&mut self) {
-        match self.status {
-            BadOpcode(_) => { return; },
-            Finished => { return; },
-            _ => {}
+    fn mem_used(&self) -> usize {
+        self.mem.len()
+    }
+
+    fn run(&mut self) -> Result<MachineStatus, MachineError> {
+        if self.status == Finished {
+            return Ok(Finished);
         }
 
         loop {
-            let opcode = self.mem[self.pos] % 100;
-            // println!("raw: {}, pos: {}, opcode: {}, addressing: {}", mem[pos], pos, opcode, addressing);
+            let opcode = self.fetch(self.pos)? % 100;
 
             match opcode {
                 1 => {
-                    let a = *self.arg(0);
-                    let b = *self.arg(1);
-                    *self.arg(2) =  a + b;
+                    let a = *self.arg(0)?;
+                    let b = *self.arg(1)?;
+                    *self.arg(2)? =  a + b;
                     self.pos += 4;
                 }
                 2 => {
-                    let a = *self.arg(0);
-                    let b = *self.arg(1);
-                    *self.arg(2) = a * b;
+                    let a = *self.arg(0)?;
+                    let b = *self.arg(1)?;
+                    *self.arg(2)? = a * b;
                     self.pos += 4;
                 }
                 3 => {
-                    if self.input_pos < self.inputs.len() {
-                        let val = self.inputs[self.input_pos];
-                        self.input_pos += 1;
-                        *self.arg(0) = val;
-                        self.pos += 2;
-                    } else {
-                        self.status = Blocked;
-                        return;
+                    match self.io.read() {
+                        Some(val) => {
+                            *self.arg(0)? = val;
+                            self.pos += 2;
+                        }
+                        None => {
+                            self.status = Blocked;
+                            return Ok(Blocked);
+                        }
                     }
                 }
                 4 => {
-                    let val = *self.arg(0);
-                    self.outputs.push(val);
+                    let val = *self.arg(0)?;
+                    self.io.write(val);
                     self.pos += 2;
                 }
                 5 => {
-                    let cond = *self.arg(0);
-                    let target = *self.arg(1);
+                    let cond = *self.arg(0)?;
+                    let target = *self.arg(1)?;
                     if cond != 0 {
                         self.pos = target as usize;
                     } else {
@@ -119,8 +238,8 @@ impl Machine {
                     }
                 }
                 6 => {
-                    let cond = *self.arg(0);
-                    let target = *self.arg(1);
+                    let cond = *self.arg(0)?;
+                    let target = *self.arg(1)?;
                     if cond == 0 {
                         self.pos = target as usize;
                     } else {
@@ -128,67 +247,175 @@ impl Machine {
                     }
                 }
                 7 => {
-                    let a = *self.arg(0);
-                    let b = *self.arg(1);
+                    let a = *self.arg(0)?;
+                    let b = *self.arg(1)?;
                     let val = (a < b) as i64;
-                    *self.arg(2) = val;
+                    *self.arg(2)? = val;
                     self.pos += 4;
                 }
                 8 => {
-                    let a = *self.arg(0);
-                    let b = *self.arg(1);
+                    let a = *self.arg(0)?;
+                    let b = *self.arg(1)?;
                     let val = (a == b) as i64;
-                    *self.arg(2) = val;
+                    *self.arg(2)? = val;
                     self.pos += 4;
                 }
                 9 => {
-                    let val = *self.arg(0);
+                    let val = *self.arg(0)?;
                     self.relative_base += val;
                     self.pos += 2;
                 }
                 99 => {
                     self.status = Finished;
-                    return;
+                    return Ok(Finished);
                 }
                 _ => {
-                    self.status = BadOpcode(opcode);
-                    return;
+                    return Err(MachineError::BadOpcode(opcode, self.pos));
                 }
             }
         }
     }
 
-    fn easy_run(&mut self, inputs: &Vec<i64>) -> &Vec<i64> {
-        self.add_inputs(inputs);
-        self.run();
-        &self.outputs
+    fn get_status(&self) -> MachineStatus {
+        self.status
     }
+}
 
-    fn add_input(&mut self, input: i64) {
-        self.inputs.push(input);
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum ParamMode {
+    Position,
+    Immediate,
+    Relative,
+}
+
+impl ParamMode {
+    fn decode(mode: i64) -> Option<ParamMode> {
+        match mode {
+            0 => Some(ParamMode::Position),
+            1 => Some(ParamMode::Immediate),
+            2 => Some(ParamMode::Relative),
+            _ => None,
+        }
     }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Operand {
+    mode: ParamMode,
+    value: i64,
+}
 
-    fn add_inputs(&mut self, inputs: &Vec<i64>) {
-        self.inputs.extend(inputs);
+impl std::fmt::Display for Operand {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.mode {
+            ParamMode::Position => write!(f, "[{}]", self.value),
+            ParamMode::Immediate => write!(f, "{}", self.value),
+            ParamMode::Relative => write!(f, "[rb{:+}]", self.value),
+        }
     }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Op {
+    Add { a: Operand, b: Operand, dst: Operand },
+    Mul { a: Operand, b: Operand, dst: Operand },
+    Input { dst: Operand },
+    Output { a: Operand },
+    JumpIfTrue { cond: Operand, target: Operand },
+    JumpIfFalse { cond: Operand, target: Operand },
+    LessThan { a: Operand, b: Operand, dst: Operand },
+    Equals { a: Operand, b: Operand, dst: Operand },
+    AdjustRelativeBase { a: Operand },
+    Halt,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Instruction {
+    pos: usize,
+    op: Op,
+}
 
-    fn get_output(&mut self) -> Option<i64> {
-        if self.output_pos < self.outputs.len() {
-            let val = self.outputs[self.output_pos];
-            self.output_pos += 1;
-            Some(val)
-        } else {
-            None
+impl std::fmt::Display for Instruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:05}: ", self.pos)?;
+        match &self.op {
+            Op::Add { a, b, dst } => write!(f, "add {}, {} -> {}", a, b, dst),
+            Op::Mul { a, b, dst } => write!(f, "mul {}, {} -> {}", a, b, dst),
+            Op::Input { dst } => write!(f, "in -> {}", dst),
+            Op::Output { a } => write!(f, "out {}", a),
+            Op::JumpIfTrue { cond, target } => write!(f, "jnz {}, {}", cond, target),
+            Op::JumpIfFalse { cond, target } => write!(f, "jz {}, {}", cond, target),
+            Op::LessThan { a, b, dst } => write!(f, "lt {}, {} -> {}", a, b, dst),
+            Op::Equals { a, b, dst } => write!(f, "eq {}, {} -> {}", a, b, dst),
+            Op::AdjustRelativeBase { a } => write!(f, "arb {}", a),
+            Op::Halt => write!(f, "halt"),
         }
     }
+}
 
-    fn get_status(&self) -> MachineStatus {
-        self.status
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum DisasmError {
+    InvalidOpcode(i64, usize),
+    InvalidMode(usize),
+    Truncated(usize),
+    ImmediateWrite(usize),
+}
+
+fn disasm_operand(mem: &[i64], pos: usize, index: usize) -> Result<Operand, DisasmError> {
+    let value = *mem.get(pos + 1 + index).ok_or(DisasmError::Truncated(pos))?;
+    let addressing = mem[pos] / 100;
+    let raw_mode = (addressing / 10_i64.pow(index as u32)) % 10;
+    let mode = ParamMode::decode(raw_mode).ok_or(DisasmError::InvalidMode(pos))?;
+    Ok(Operand { mode, value })
+}
+
+fn disasm_write_operand(mem: &[i64], pos: usize, index: usize) -> Result<Operand, DisasmError> {
+    let operand = disasm_operand(mem, pos, index)?;
+    if operand.mode == ParamMode::Immediate {
+        return Err(DisasmError::ImmediateWrite(pos));
     }
+    Ok(operand)
+}
+
+/// Decodes an Intcode program into a sequence of instructions, the same way a
+/// bytecode disassembler decodes a register VM's program.
+fn disasm(mem: &[i64]) -> Result<Vec<Instruction>, DisasmError> {
+    let mut instructions = Vec::new();
+    let mut pos = 0;
+
+    while pos < mem.len() {
+        let opcode = mem[pos] % 100;
+        let (op, len) = match opcode {
+            1 => (Op::Add { a: disasm_operand(mem, pos, 0)?, b: disasm_operand(mem, pos, 1)?, dst: disasm_write_operand(mem, pos, 2)? }, 4),
+            2 => (Op::Mul { a: disasm_operand(mem, pos, 0)?, b: disasm_operand(mem, pos, 1)?, dst: disasm_write_operand(mem, pos, 2)? }, 4),
+            3 => (Op::Input { dst: disasm_write_operand(mem, pos, 0)? }, 2),
+            4 => (Op::Output { a: disasm_operand(mem, pos, 0)? }, 2),
+            5 => (Op::JumpIfTrue { cond: disasm_operand(mem, pos, 0)?, target: disasm_operand(mem, pos, 1)? }, 3),
+            6 => (Op::JumpIfFalse { cond: disasm_operand(mem, pos, 0)?, target: disasm_operand(mem, pos, 1)? }, 3),
+            7 => (Op::LessThan { a: disasm_operand(mem, pos, 0)?, b: disasm_operand(mem, pos, 1)?, dst: disasm_write_operand(mem, pos, 2)? }, 4),
+            8 => (Op::Equals { a: disasm_operand(mem, pos, 0)?, b: disasm_operand(mem, pos, 1)?, dst: disasm_write_operand(mem, pos, 2)? }, 4),
+            9 => (Op::AdjustRelativeBase { a: disasm_operand(mem, pos, 0)? }, 2),
+            99 => (Op::Halt, 1),
+            _ => return Err(DisasmError::InvalidOpcode(opcode, pos)),
+        };
+
+        instructions.push(Instruction { pos, op });
+        pos += len;
+    }
+
+    Ok(instructions)
+}
+
+fn print_disasm(mem: &[i64]) -> Result<(), DisasmError> {
+    for instruction in disasm(mem)? {
+        println!("{}", instruction);
+    }
+    Ok(())
 }
 
 struct World {
-    machine:  Machine,
+    machine: Machine,
+    io: QueueHandle,
     tiles: HashMap<(i64, i64), i64>,
     score: i64,
 
@@ -197,9 +424,11 @@ struct World {
 }
 
 impl World {
-    fn new(machine: Machine) -> World {
+    fn new(mem: &Vec<i64>) -> World {
+        let (io, handle) = queue_io();
         World {
-            machine,
+            machine: Machine::new(mem, Box::new(io)),
+            io: handle,
             tiles: HashMap::new(),
             score: 0,
             paddle_x: 0,
@@ -226,27 +455,28 @@ impl World {
             println!();
         }
         println!("Score: {}", self.score);
+        println!("Memory used: {} cells", self.machine.mem_used());
     }
 
-    fn process(&mut self) {
-        self.machine.run();
-        let output = &self.machine.outputs;
+    fn process(&mut self) -> Result<(), MachineError> {
+        self.machine.run()?;
 
-        for chunk in output.chunks(3) {
+        for chunk in self.io.drain_outputs().chunks(3) {
             let x = chunk[0];
             let y = chunk[1];
             let tile = chunk[2];
             self.tiles.insert((x, y), tile);
         }
+
+        Ok(())
     }
 
-    fn play(&mut self) {
+    fn play(&mut self) -> Result<(), MachineError> {
         self.machine.mem[0] = 2;
 
         loop {
-            self.machine.outputs.clear();
-            self.machine.run();
-            let output = &self.machine.outputs;
+            self.machine.run()?;
+            let output = self.io.drain_outputs();
 
             // Process output.
             for chunk in output.chunks(3) {
@@ -268,15 +498,15 @@ impl World {
             }
 
             if self.count_blocks() == 0 {
-                return;
+                return Ok(());
             }
 
             if self.machine.status == MachineStatus::Finished {
                 println!("Finished.");
-                return;
+                return Ok(());
             }
 
-            self.machine.add_input(match self.ball_x.cmp(&self.paddle_x) {
+            self.io.push_input(match self.ball_x.cmp(&self.paddle_x) {
                 Ordering::Less => -1,
                 Ordering::Equal => 0,
                 Ordering::Greater => 1,
@@ -299,16 +529,19 @@ fn main() {
     let line = read_lines("input.in").nth(0).unwrap();
     let mem: Vec<i64> = line.split(",").map(|x| x.parse::<i64>().unwrap()).collect();
 
+    if std::env::args().any(|a| a == "--disasm") {
+        print_disasm(&mem).unwrap();
+        return;
+    }
+
     // Part 1.
-    let machine = Machine::new(&mem);
-    let mut world = World::new(machine);
-    world.process();
+    let mut world = World::new(&mem);
+    world.process().unwrap();
     println!("Num Blocks: {}", world.count_blocks());
     world.print();
 
     // Part 2.
-    let machine = Machine::new(&mem);
-    let mut world = World::new(machine);
-    world.play();
+    let mut world = World::new(&mem);
+    world.play().unwrap();
     world.print();
 }